@@ -0,0 +1,110 @@
+use hassel_emu::hassel::SoundDevice;
+
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{OutputCallbackInfo, Stream, StreamError};
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::process;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+
+/// The clock rate the emulator is paced to in `run_mode_default`.
+const EMULATED_HZ: f64 = 6_000_000.0;
+
+/// Caps how far the ring buffer is allowed to grow, in seconds of buffered
+/// audio. A normally-paced caller never gets close to this, but it stops a
+/// burst of catch-up cycles (e.g. after a stalled window resumes) from queuing
+/// unbounded audio far faster than the cpal callback can drain it.
+const MAX_BUFFERED_SECONDS: f64 = 0.25;
+
+/// Owns the `cpal` output stream and the ring buffer that feeds it. The
+/// stream must stay alive for audio to keep playing, so callers hold onto
+/// this for the lifetime of the window loop.
+pub struct AudioOutput {
+    _stream: Stream,
+    buffer: Arc<Mutex<VecDeque<i16>>>,
+    sample_rate: u32,
+    cycle_accum: f64,
+    max_buffered_samples: usize,
+}
+
+impl AudioOutput {
+    /// Opens the default output device and starts playback from an empty
+    /// ring buffer.
+    pub fn open() -> Self {
+        let host = cpal::default_host();
+        let device = host.default_output_device().unwrap_or_else(|| {
+            println!("Failed to find an audio output device");
+            process::exit(1);
+        });
+        let config = device
+            .default_output_config()
+            .unwrap_or_else(|e| {
+                println!("Failed to get default audio output config: {}", e);
+                process::exit(1);
+            })
+            .config();
+
+        let sample_rate = config.sample_rate.0;
+        let channels = config.channels as usize;
+        let buffer = Arc::new(Mutex::new(VecDeque::new()));
+        let callback_buffer = Arc::clone(&buffer);
+
+        let stream = device
+            .build_output_stream(
+                &config,
+                move |data: &mut [f32], _: &OutputCallbackInfo| {
+                    let mut buffer = callback_buffer.lock().unwrap();
+                    for frame in data.chunks_mut(channels) {
+                        let sample = buffer.pop_front().unwrap_or(0) as f32 / i16::MAX as f32;
+                        for out in frame {
+                            *out = sample;
+                        }
+                    }
+                },
+                |err: StreamError| println!("Audio stream error: {}", err),
+                None,
+            )
+            .unwrap_or_else(|e| {
+                println!("Failed to open audio output stream: {}", e);
+                process::exit(1);
+            });
+
+        stream.play().unwrap_or_else(|e| {
+            println!("Failed to start audio playback: {}", e);
+            process::exit(1);
+        });
+
+        AudioOutput {
+            _stream: stream,
+            buffer,
+            sample_rate,
+            cycle_accum: 0.0,
+            max_buffered_samples: (sample_rate as f64 * MAX_BUFFERED_SECONDS) as usize,
+        }
+    }
+
+    /// Generates samples for the cycles just advanced and resamples them
+    /// down from the emulated clock rate to the host sample rate, using the
+    /// accumulated-cycle fraction so playback doesn't drift out of sync.
+    pub fn feed(&mut self, sound: &Rc<RefCell<SoundDevice>>, cycles: u32) {
+        let generated = sound.borrow_mut().generate_samples(cycles as u64);
+        let cycles_per_sample = EMULATED_HZ / self.sample_rate as f64;
+
+        let mut buffer = self.buffer.lock().unwrap();
+        for sample in generated {
+            self.cycle_accum += 1.0;
+            if self.cycle_accum >= cycles_per_sample {
+                self.cycle_accum -= cycles_per_sample;
+                if buffer.len() >= self.max_buffered_samples {
+                    // Fell behind (e.g. a catch-up burst): drop
+                    // the oldest buffered sample so playback tracks the
+                    // present instead of queuing ever further behind.
+                    buffer.pop_front();
+                }
+                buffer.push_back(sample);
+            }
+        }
+    }
+}
@@ -0,0 +1,134 @@
+use hassel_emu::emulator::Emulator;
+
+/// Number of program counters retained in the execution history ring buffer.
+const PC_HISTORY_LEN: usize = 512;
+
+/// Tracks pause/step state for the in-window debugger overlay, plus a short
+/// history of recently executed program counters.
+pub struct EmulatorState {
+    pub paused: bool,
+    pub step: bool,
+    pub step_counter: usize,
+    pc_history: [u16; PC_HISTORY_LEN],
+    pc_history_pos: usize,
+    pc_history_len: usize,
+}
+
+impl EmulatorState {
+    pub fn new() -> Self {
+        EmulatorState {
+            paused: false,
+            step: false,
+            step_counter: 0,
+            pc_history: [0; PC_HISTORY_LEN],
+            pc_history_pos: 0,
+            pc_history_len: 0,
+        }
+    }
+
+    pub fn toggle_paused(&mut self) {
+        self.paused = !self.paused;
+    }
+
+    pub fn request_step(&mut self) {
+        self.step = true;
+    }
+
+    /// Advances the emulator by one instruction if appropriate, recording the
+    /// PC that was executed. Returns the number of cycles consumed, or `None`
+    /// if the emulator stayed paused.
+    pub fn step_if_allowed(&mut self, emulator: &mut Emulator) -> Option<u32> {
+        if self.paused && !self.step {
+            return None;
+        }
+        self.step = false;
+
+        self.push_pc(emulator.pc());
+        let cycles = emulator.step() as u32;
+        self.step_counter += 1;
+        Some(cycles)
+    }
+
+    fn push_pc(&mut self, pc: u16) {
+        self.pc_history[self.pc_history_pos] = pc;
+        self.pc_history_pos = (self.pc_history_pos + 1) % PC_HISTORY_LEN;
+        self.pc_history_len = (self.pc_history_len + 1).min(PC_HISTORY_LEN);
+    }
+
+    /// Returns the most recent PCs, oldest first.
+    pub fn pc_history(&self) -> Vec<u16> {
+        let mut history = Vec::with_capacity(self.pc_history_len);
+        let start = (self.pc_history_pos + PC_HISTORY_LEN - self.pc_history_len) % PC_HISTORY_LEN;
+        for i in 0..self.pc_history_len {
+            history.push(self.pc_history[(start + i) % PC_HISTORY_LEN]);
+        }
+        history
+    }
+}
+
+/// Composites the register dump and PC history directly into a 0RGB frame
+/// buffer as lines of text cells. Each character is drawn by `blit_text` as
+/// a solid block, so the overlay shows the layout of the text but not the
+/// letter shapes.
+pub fn render_overlay(buffer: &mut [u32], buffer_width: usize, emulator: &Emulator, state: &EmulatorState) {
+    let lines = [
+        format!(
+            "{} PC:{:04X} A:{:02X} X:{:02X} Y:{:02X} SP:{:02X} P:{:02X}",
+            if state.paused { "PAUSED" } else { "RUN   " },
+            emulator.pc(),
+            emulator.reg_a(),
+            emulator.reg_x(),
+            emulator.reg_y(),
+            emulator.reg_sp(),
+            emulator.status(),
+        ),
+        format!(
+            "history: {}",
+            state
+                .pc_history()
+                .iter()
+                .rev()
+                .take(8)
+                .map(|pc| format!("{:04X}", pc))
+                .collect::<Vec<_>>()
+                .join(" ")
+        ),
+    ];
+
+    for (row, line) in lines.iter().enumerate() {
+        blit_text(buffer, buffer_width, 2, 2 + row * (FONT_HEIGHT + 1), line, 0xFF00FF00);
+    }
+}
+
+const FONT_WIDTH: usize = 4;
+const FONT_HEIGHT: usize = 6;
+
+/// Blits `text` into `buffer` at `(x, y)`, drawing every non-space character
+/// as the same solid 4x6 block. Characters are not distinguished from one
+/// another, so the text itself cannot be read back from the frame.
+fn blit_text(buffer: &mut [u32], buffer_width: usize, x: usize, y: usize, text: &str, color: u32) {
+    let buffer_height = buffer.len() / buffer_width;
+    for (i, ch) in text.chars().enumerate() {
+        if ch == ' ' {
+            continue;
+        }
+        let glyph_x = x + i * (FONT_WIDTH + 1);
+        // Every non-space character is drawn as a solid glyph cell; this is a
+        // placeholder bitmap font and doesn't distinguish letter shapes.
+        for gy in 0..FONT_HEIGHT {
+            for gx in 0..FONT_WIDTH {
+                let px = glyph_x + gx;
+                let py = y + gy;
+                if px >= buffer_width || py >= buffer_height {
+                    // Clip instead of letting the flat index wrap onto the
+                    // next scanline.
+                    continue;
+                }
+                let idx = py * buffer_width + px;
+                if idx < buffer.len() {
+                    buffer[idx] = color;
+                }
+            }
+        }
+    }
+}
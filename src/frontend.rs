@@ -0,0 +1,421 @@
+use crate::audio::AudioOutput;
+use crate::debugger::{self, EmulatorState};
+use crate::movie;
+use crate::save_state;
+use crate::trace;
+
+use hassel_emu::emulator::Emulator;
+use hassel_emu::hassel::{
+    GraphicsDevice, IODevice, Key, SoundDevice, SCREEN_HEIGHT_PIXELS, SCREEN_WIDTH_PIXELS,
+};
+
+use pixels::{Pixels, SurfaceTexture};
+use winit::dpi::LogicalSize;
+use winit::event::{ElementState, Event, VirtualKeyCode, WindowEvent};
+use winit::event_loop::{ControlFlow, EventLoop};
+use winit::window::{Fullscreen, WindowBuilder};
+
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::process;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+/// Bundles the options that shape how `run` behaves, since the window loop
+/// has grown enough knobs that threading them through as individual
+/// parameters got unwieldy. The winit event loop requires a `'static`
+/// closure, so this owns its strings instead of borrowing from
+/// `ArgMatches`.
+pub struct RunConfig {
+    pub rom_path: String,
+    pub rom_len: usize,
+    pub rom_checksum: u64,
+    pub record_path: Option<String>,
+    pub play_path: Option<String>,
+    pub trace_path: Option<String>,
+    pub trace_range: Option<(u16, u16)>,
+    pub scale: u32,
+}
+
+const EMULATED_HZ: f64 = 6_000_000.0;
+/// Caps how much emulated time a single tick is allowed to catch up on, so a
+/// stalled or minimized window doesn't cause a burst of instructions to run
+/// all at once when it regains focus.
+const MAX_CATCH_UP: Duration = Duration::from_millis(250);
+/// Target interval between redraws, matching the ~76 Hz cap the old minifb
+/// loop used.
+const RENDER_INTERVAL: Duration = Duration::from_millis(13);
+
+/// Runs the interactive window loop. Rendering goes through `pixels` onto a
+/// `winit` window, so the window can be freely resized or made fullscreen
+/// while the emulator keeps rendering at its native resolution underneath;
+/// the GPU handles the upscale.
+pub fn run(
+    mut emulator: Emulator,
+    graphics: Rc<RefCell<GraphicsDevice>>,
+    io: Rc<RefCell<IODevice>>,
+    sound: Rc<RefCell<SoundDevice>>,
+    config: RunConfig,
+) {
+    let state_path = save_state::state_path(&config.rom_path);
+    let mut audio_output = AudioOutput::open();
+
+    let mut recorder = config.record_path.as_ref().map(|_| movie::MovieRecorder::new());
+    let mut player = config.play_path.as_ref().map(|path| {
+        movie::MoviePlayer::load_from_file(path, config.rom_len, config.rom_checksum)
+            .unwrap_or_else(|e| {
+                println!("Failed to load movie \"{}\": {}", path, e);
+                process::exit(1);
+            })
+    });
+    let mut trace_writer = config.trace_path.as_ref().map(|path| {
+        trace::TraceWriter::open(path, config.trace_range).unwrap_or_else(|e| {
+            println!("Failed to open trace file \"{}\": {}", path, e);
+            process::exit(1);
+        })
+    });
+
+    let event_loop = EventLoop::new();
+    let mut window = {
+        let initial_size = LogicalSize::new(
+            (SCREEN_WIDTH_PIXELS * config.scale as usize) as f64,
+            (SCREEN_HEIGHT_PIXELS * config.scale as usize) as f64,
+        );
+        let native_size = LogicalSize::new(SCREEN_WIDTH_PIXELS as f64, SCREEN_HEIGHT_PIXELS as f64);
+        WindowBuilder::new()
+            .with_title("Hasseldorf Emulator")
+            .with_inner_size(initial_size)
+            .with_min_inner_size(native_size)
+            .build(&event_loop)
+            .unwrap_or_else(|e| {
+                println!("Failed to create a window: {}", e);
+                process::exit(1);
+            })
+    };
+
+    let mut pixels = {
+        let window_size = window.inner_size();
+        let surface_texture = SurfaceTexture::new(window_size.width, window_size.height, &window);
+        Pixels::new(
+            SCREEN_WIDTH_PIXELS as u32,
+            SCREEN_HEIGHT_PIXELS as u32,
+            surface_texture,
+        )
+        .unwrap_or_else(|e| {
+            println!("Failed to set up the renderer: {}", e);
+            process::exit(1);
+        })
+    };
+
+    let mut time_last_step = Instant::now();
+    let mut time_last_render = Instant::now();
+    let mut cycle_budget: f64 = 0.0;
+    let mut total_cycles: usize = 0;
+    let mut keys_down: HashSet<VirtualKeyCode> = HashSet::new();
+    let mut debug_state = EmulatorState::new();
+
+    event_loop.run(move |event, _, control_flow| {
+        *control_flow = ControlFlow::Poll;
+
+        match event {
+            Event::WindowEvent { event: window_event, .. } => match window_event {
+                WindowEvent::CloseRequested => {
+                    finalize(&mut recorder, &mut trace_writer, &config);
+                    *control_flow = ControlFlow::Exit;
+                }
+                WindowEvent::Resized(size) => {
+                    if let Err(e) = pixels.resize_surface(size.width, size.height) {
+                        println!("Failed to resize the renderer: {}", e);
+                    }
+                }
+                WindowEvent::KeyboardInput { input, .. } => {
+                    let keycode = match input.virtual_keycode {
+                        Some(keycode) => keycode,
+                        None => return,
+                    };
+
+                    match input.state {
+                        ElementState::Pressed => {
+                            if !keys_down.insert(keycode) {
+                                return;
+                            }
+                            match keycode {
+                                VirtualKeyCode::F12 => debug_state.toggle_paused(),
+                                VirtualKeyCode::F11 => debug_state.request_step(),
+                                VirtualKeyCode::F5 => save_state::save(&emulator, &state_path),
+                                VirtualKeyCode::F9 => save_state::load(&mut emulator, &state_path),
+                                VirtualKeyCode::Return
+                                    if keys_down.contains(&VirtualKeyCode::LAlt)
+                                        || keys_down.contains(&VirtualKeyCode::RAlt) =>
+                                {
+                                    toggle_fullscreen(&mut window);
+                                }
+                                _ => {
+                                    if player.is_none() {
+                                        let converted = convert_key(keycode);
+                                        io.borrow_mut().key_down(converted);
+                                        if let Some(recorder) = recorder.as_mut() {
+                                            recorder.record(total_cycles as u64, converted, true);
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                        ElementState::Released => {
+                            keys_down.remove(&keycode);
+                            match keycode {
+                                VirtualKeyCode::F12
+                                | VirtualKeyCode::F11
+                                | VirtualKeyCode::F5
+                                | VirtualKeyCode::F9
+                                | VirtualKeyCode::Return => {}
+                                _ => {
+                                    if player.is_none() {
+                                        let converted = convert_key(keycode);
+                                        io.borrow_mut().key_up(converted);
+                                        if let Some(recorder) = recorder.as_mut() {
+                                            recorder.record(total_cycles as u64, converted, false);
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+                _ => {}
+            },
+            Event::MainEventsCleared => {
+                let elapsed = time_last_step.elapsed().min(MAX_CATCH_UP);
+                time_last_step = Instant::now();
+
+                // While paused, the virtual clock must not keep running:
+                // otherwise the wall-clock time spent paused accumulates as
+                // a cycle backlog that gets mass-executed in a burst the
+                // moment the emulator resumes. A single step (F11) still
+                // needs just enough budget for the one instruction it asks
+                // for.
+                if debug_state.paused {
+                    cycle_budget = if debug_state.step { 1.0 } else { 0.0 };
+                } else {
+                    cycle_budget += elapsed.as_secs_f64() * EMULATED_HZ;
+                }
+
+                while cycle_budget > 0.0 {
+                    if let Some(player) = player.as_mut() {
+                        player.dispatch_due(total_cycles as u64, &io);
+                    }
+
+                    let trace_entry = trace_writer
+                        .as_ref()
+                        .filter(|writer| writer.wants(emulator.pc()))
+                        .map(|_| trace::snapshot(&emulator));
+
+                    let cycles = match debug_state.step_if_allowed(&mut emulator) {
+                        Some(cycles) => cycles,
+                        None => {
+                            cycle_budget = 0.0;
+                            break;
+                        }
+                    };
+                    total_cycles += cycles as usize;
+                    cycle_budget -= cycles as f64;
+                    audio_output.feed(&sound, cycles);
+
+                    if let (Some(writer), Some(entry)) = (trace_writer.as_mut(), trace_entry) {
+                        if let Err(e) = writer.write(&entry, total_cycles as u64) {
+                            println!("Failed to write trace: {}", e);
+                        }
+                    }
+                }
+
+                if time_last_render.elapsed() >= RENDER_INTERVAL {
+                    time_last_render = Instant::now();
+                    window.request_redraw();
+                }
+            }
+            Event::RedrawRequested(_) => {
+                let mut frame = graphics.borrow().frame_buffer().to_vec();
+                if debug_state.paused {
+                    debugger::render_overlay(&mut frame, SCREEN_WIDTH_PIXELS, &emulator, &debug_state);
+                }
+                write_rgba_frame(pixels.frame_mut(), &frame);
+                if let Err(e) = pixels.render() {
+                    println!("Render failed: {}", e);
+                    finalize(&mut recorder, &mut trace_writer, &config);
+                    *control_flow = ControlFlow::Exit;
+                }
+            }
+            _ => {}
+        }
+    });
+}
+
+/// Flushes the movie recorder and trace writer to disk. `EventLoop::run`
+/// never returns, so neither one's `Drop` impl is guaranteed to run on
+/// exit -- every path that sets `ControlFlow::Exit` must call this or the
+/// tail of a recording/trace capture is silently lost.
+fn finalize(
+    recorder: &mut Option<movie::MovieRecorder>,
+    trace_writer: &mut Option<trace::TraceWriter>,
+    config: &RunConfig,
+) {
+    if let (Some(recorder), Some(path)) = (recorder.take(), config.record_path.as_ref()) {
+        if let Err(e) = recorder.write_to_file(path, config.rom_len, config.rom_checksum) {
+            println!("Failed to write movie \"{}\": {}", path, e);
+        }
+    }
+    if let Some(writer) = trace_writer.take() {
+        if let Err(e) = writer.finish() {
+            println!("Failed to flush trace: {}", e);
+        }
+    }
+}
+
+fn toggle_fullscreen(window: &mut winit::window::Window) {
+    if window.fullscreen().is_some() {
+        window.set_fullscreen(None);
+    } else {
+        window.set_fullscreen(Some(Fullscreen::Borderless(None)));
+    }
+}
+
+/// Converts a native 0RGB frame buffer (the format `GraphicsDevice` and the
+/// debugger overlay both use) into the packed RGBA8 bytes `pixels` expects.
+fn write_rgba_frame(dest: &mut [u8], src: &[u32]) {
+    for (pixel, chunk) in src.iter().zip(dest.chunks_exact_mut(4)) {
+        chunk[0] = ((pixel >> 16) & 0xFF) as u8;
+        chunk[1] = ((pixel >> 8) & 0xFF) as u8;
+        chunk[2] = (pixel & 0xFF) as u8;
+        chunk[3] = 0xFF;
+    }
+}
+
+fn convert_key(key: VirtualKeyCode) -> Key {
+    match key {
+        VirtualKeyCode::Key0 => Key::Key0,
+        VirtualKeyCode::Key1 => Key::Key1,
+        VirtualKeyCode::Key2 => Key::Key2,
+        VirtualKeyCode::Key3 => Key::Key3,
+        VirtualKeyCode::Key4 => Key::Key4,
+        VirtualKeyCode::Key5 => Key::Key5,
+        VirtualKeyCode::Key6 => Key::Key6,
+        VirtualKeyCode::Key7 => Key::Key7,
+        VirtualKeyCode::Key8 => Key::Key8,
+        VirtualKeyCode::Key9 => Key::Key9,
+
+        VirtualKeyCode::A => Key::A,
+        VirtualKeyCode::B => Key::B,
+        VirtualKeyCode::C => Key::C,
+        VirtualKeyCode::D => Key::D,
+        VirtualKeyCode::E => Key::E,
+        VirtualKeyCode::F => Key::F,
+        VirtualKeyCode::G => Key::G,
+        VirtualKeyCode::H => Key::H,
+        VirtualKeyCode::I => Key::I,
+        VirtualKeyCode::J => Key::J,
+        VirtualKeyCode::K => Key::K,
+        VirtualKeyCode::L => Key::L,
+        VirtualKeyCode::M => Key::M,
+        VirtualKeyCode::N => Key::N,
+        VirtualKeyCode::O => Key::O,
+        VirtualKeyCode::P => Key::P,
+        VirtualKeyCode::Q => Key::Q,
+        VirtualKeyCode::R => Key::R,
+        VirtualKeyCode::S => Key::S,
+        VirtualKeyCode::T => Key::T,
+        VirtualKeyCode::U => Key::U,
+        VirtualKeyCode::V => Key::V,
+        VirtualKeyCode::W => Key::W,
+        VirtualKeyCode::X => Key::X,
+        VirtualKeyCode::Y => Key::Y,
+        VirtualKeyCode::Z => Key::Z,
+
+        VirtualKeyCode::Space => Key::Space,
+        VirtualKeyCode::Tab => Key::Tab,
+
+        VirtualKeyCode::Backslash => Key::Backslash,
+        VirtualKeyCode::Comma => Key::Comma,
+        VirtualKeyCode::Equals => Key::Equal,
+        VirtualKeyCode::LBracket => Key::LeftBracket,
+        VirtualKeyCode::Minus => Key::Minus,
+        VirtualKeyCode::Period => Key::Period,
+        VirtualKeyCode::RBracket => Key::RightBracket,
+        VirtualKeyCode::Semicolon => Key::Semicolon,
+
+        VirtualKeyCode::Slash => Key::Slash,
+        VirtualKeyCode::Return => Key::Enter,
+
+        VirtualKeyCode::Back => Key::Backspace,
+        VirtualKeyCode::Delete => Key::Delete,
+        VirtualKeyCode::End => Key::End,
+
+        VirtualKeyCode::F1 => Key::F1,
+        VirtualKeyCode::F2 => Key::F2,
+        VirtualKeyCode::F3 => Key::F3,
+        VirtualKeyCode::F4 => Key::F4,
+        VirtualKeyCode::F5 => Key::F5,
+        VirtualKeyCode::F6 => Key::F6,
+        VirtualKeyCode::F7 => Key::F7,
+        VirtualKeyCode::F8 => Key::F8,
+        VirtualKeyCode::F9 => Key::F9,
+        VirtualKeyCode::F10 => Key::F10,
+        VirtualKeyCode::F11 => Key::F11,
+        VirtualKeyCode::F12 => Key::F12,
+        VirtualKeyCode::F13 => Key::F13,
+        VirtualKeyCode::F14 => Key::F14,
+        VirtualKeyCode::F15 => Key::F15,
+
+        VirtualKeyCode::Down => Key::Down,
+        VirtualKeyCode::Left => Key::Left,
+        VirtualKeyCode::Right => Key::Right,
+        VirtualKeyCode::Up => Key::Up,
+        VirtualKeyCode::Apostrophe => Key::Apostrophe,
+        VirtualKeyCode::Grave => Key::Backquote,
+
+        VirtualKeyCode::Escape => Key::Escape,
+
+        VirtualKeyCode::Home => Key::Home,
+        VirtualKeyCode::Insert => Key::Insert,
+
+        VirtualKeyCode::PageDown => Key::PageDown,
+        VirtualKeyCode::PageUp => Key::PageUp,
+
+        VirtualKeyCode::Pause => Key::Pause,
+        VirtualKeyCode::Numlock => Key::NumLock,
+        VirtualKeyCode::Capital => Key::CapsLock,
+        VirtualKeyCode::Scroll => Key::ScrollLock,
+        VirtualKeyCode::LShift => Key::LeftShift,
+        VirtualKeyCode::RShift => Key::RightShift,
+        VirtualKeyCode::LControl => Key::LeftCtrl,
+        VirtualKeyCode::RControl => Key::RightCtrl,
+
+        VirtualKeyCode::Numpad0 => Key::NumPad0,
+        VirtualKeyCode::Numpad1 => Key::NumPad1,
+        VirtualKeyCode::Numpad2 => Key::NumPad2,
+        VirtualKeyCode::Numpad3 => Key::NumPad3,
+        VirtualKeyCode::Numpad4 => Key::NumPad4,
+        VirtualKeyCode::Numpad5 => Key::NumPad5,
+        VirtualKeyCode::Numpad6 => Key::NumPad6,
+        VirtualKeyCode::Numpad7 => Key::NumPad7,
+        VirtualKeyCode::Numpad8 => Key::NumPad8,
+        VirtualKeyCode::Numpad9 => Key::NumPad9,
+        VirtualKeyCode::NumpadDecimal => Key::NumPadDot,
+        VirtualKeyCode::NumpadDivide => Key::NumPadSlash,
+        VirtualKeyCode::NumpadMultiply => Key::NumPadAsterisk,
+        VirtualKeyCode::NumpadSubtract => Key::NumPadMinus,
+        VirtualKeyCode::NumpadAdd => Key::NumPadPlus,
+        VirtualKeyCode::NumpadEnter => Key::NumPadEnter,
+
+        VirtualKeyCode::LAlt => Key::LeftAlt,
+        VirtualKeyCode::RAlt => Key::RightAlt,
+
+        VirtualKeyCode::LWin => Key::LeftSuper,
+        VirtualKeyCode::RWin => Key::RightSuper,
+
+        // The real `VirtualKeyCode` enum covers far more than the
+        // Hasseldorf keyboard cares about (media keys, OEM keys, IME
+        // keys, etc.); anything not listed above has no meaningful
+        // mapping.
+        _ => Key::Unknown,
+    }
+}
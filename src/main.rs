@@ -1,23 +1,27 @@
 extern crate clap;
+extern crate cpal;
 extern crate hassel_emu;
-extern crate minifb;
-
-use hassel_emu::hassel::{GraphicsDevice, HasselSystemBuilder, IODevice, Key, REQUIRED_ROM_SIZE,
-                         SCREEN_HEIGHT_PIXELS, SCREEN_WIDTH_PIXELS};
+extern crate pixels;
+extern crate winit;
+
+mod audio;
+mod debugger;
+mod frontend;
+mod movie;
+mod save_state;
+mod test_runner;
+mod trace;
+
+use hassel_emu::hassel::{HasselSystemBuilder, REQUIRED_ROM_SIZE};
 use hassel_emu::emulator::Emulator;
 
 use clap::{App, Arg, SubCommand};
-use minifb::{Window, WindowOptions};
 
-use std::env;
 use std::fs::File;
 use std::io::prelude::*;
 use std::time::Instant;
 use std::process;
 
-use std::cell::RefCell;
-use std::rc::Rc;
-
 fn load_rom(rom_path: &str) -> Result<Vec<u8>, String> {
     println!("Loading rom named \"{}\"...", rom_path);
     let mut rom_file =
@@ -40,7 +44,7 @@ fn load_rom(rom_path: &str) -> Result<Vec<u8>, String> {
 }
 
 fn main() {
-    let matches = App::new("Hasseldorf Emulator (with minifb)")
+    let matches = App::new("Hasseldorf Emulator")
         .version("0.1")
         .author("John DiSanti <johndisanti@gmail.com>")
         .about("Emulates ROMs for the homebrew Hasseldorf Computer")
@@ -54,6 +58,85 @@ fn main() {
                 .long("bench")
                 .help("Run in benchmark mode (to performance test the emulator)"),
         )
+        .arg(
+            Arg::with_name("record")
+                .long("record")
+                .takes_value(true)
+                .help("Records keyboard input to a movie file for deterministic playback"),
+        )
+        .arg(
+            Arg::with_name("play")
+                .long("play")
+                .takes_value(true)
+                .help("Plays back a previously recorded movie file instead of reading the keyboard"),
+        )
+        .arg(
+            Arg::with_name("trace")
+                .long("trace")
+                .takes_value(true)
+                .help("Writes one line per executed instruction to the given file"),
+        )
+        .arg(
+            Arg::with_name("trace-range")
+                .long("trace-range")
+                .takes_value(true)
+                .help("Restricts --trace to PCs in the inclusive LO:HI hex range, e.g. 8000:80ff"),
+        )
+        .arg(
+            Arg::with_name("scale")
+                .long("scale")
+                .takes_value(true)
+                .default_value("2")
+                .help("Initial integer scale factor for the window (it can still be freely resized or made fullscreen afterward)"),
+        )
+        .subcommand(
+            SubCommand::with_name("trace")
+                .about("Runs headlessly to a cycle cap, writing an instruction trace")
+                .arg(
+                    Arg::with_name("max-cycles")
+                        .long("max-cycles")
+                        .takes_value(true)
+                        .default_value("20000000")
+                        .help("Maximum number of cycles to execute"),
+                )
+                .arg(
+                    Arg::with_name("out")
+                        .long("out")
+                        .takes_value(true)
+                        .required(true)
+                        .help("Where to write the instruction trace"),
+                )
+                .arg(
+                    Arg::with_name("range")
+                        .long("range")
+                        .takes_value(true)
+                        .help("Restricts the trace to PCs in the inclusive LO:HI hex range, e.g. 8000:80ff"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("test")
+                .about("Runs headlessly to a cycle cap and diffs the output against a golden file")
+                .arg(
+                    Arg::with_name("max-cycles")
+                        .long("max-cycles")
+                        .takes_value(true)
+                        .default_value("20000000")
+                        .help("Maximum number of cycles to execute before capturing output"),
+                )
+                .arg(
+                    Arg::with_name("expected")
+                        .long("expected")
+                        .takes_value(true)
+                        .help("Golden file to diff the captured output against; omit to regenerate"),
+                )
+                .arg(
+                    Arg::with_name("out")
+                        .long("out")
+                        .takes_value(true)
+                        .default_value("test_output.txt")
+                        .help("Where to write the captured output when --expected is omitted"),
+                ),
+        )
         .get_matches();
 
     let rom_path = matches.value_of("ROM").unwrap();
@@ -65,14 +148,73 @@ fn main() {
         }
     };
 
-    let (memory, graphics, io) = HasselSystemBuilder::new().rom(rom).build();
+    let rom_len = rom.len();
+    let rom_checksum = movie::rom_checksum(&rom);
+
+    let (memory, graphics, io, sound) = HasselSystemBuilder::new().rom(rom).build();
     let mut emulator = Emulator::new(memory);
     emulator.reset();
 
-    if matches.is_present("bench") {
+    if let Some(test_matches) = matches.subcommand_matches("test") {
+        let max_cycles: usize = test_matches
+            .value_of("max-cycles")
+            .unwrap()
+            .parse()
+            .unwrap_or_else(|_| {
+                println!("--max-cycles must be a number");
+                process::exit(1);
+            });
+        test_runner::run(
+            emulator,
+            graphics,
+            max_cycles,
+            test_matches.value_of("expected"),
+            test_matches.value_of("out").unwrap(),
+        );
+    } else if let Some(trace_matches) = matches.subcommand_matches("trace") {
+        let max_cycles: usize = trace_matches
+            .value_of("max-cycles")
+            .unwrap()
+            .parse()
+            .unwrap_or_else(|_| {
+                println!("--max-cycles must be a number");
+                process::exit(1);
+            });
+        let range = trace_matches.value_of("range").map(|spec| {
+            trace::parse_range(spec).unwrap_or_else(|e| {
+                println!("Invalid --range: {}", e);
+                process::exit(1);
+            })
+        });
+        run_mode_trace(emulator, max_cycles, trace_matches.value_of("out").unwrap(), range);
+    } else if matches.is_present("bench") {
         run_mode_benchmark(emulator);
     } else {
-        run_mode_default(emulator, graphics, io);
+        let trace_range = matches.value_of("trace-range").map(|spec| {
+            trace::parse_range(spec).unwrap_or_else(|e| {
+                println!("Invalid --trace-range: {}", e);
+                process::exit(1);
+            })
+        });
+        let scale: u32 = matches
+            .value_of("scale")
+            .unwrap()
+            .parse()
+            .unwrap_or_else(|_| {
+                println!("--scale must be a number");
+                process::exit(1);
+            });
+        let config = frontend::RunConfig {
+            rom_path: rom_path.to_string(),
+            rom_len,
+            rom_checksum,
+            record_path: matches.value_of("record").map(|s| s.to_string()),
+            play_path: matches.value_of("play").map(|s| s.to_string()),
+            trace_path: matches.value_of("trace").map(|s| s.to_string()),
+            trace_range,
+            scale,
+        };
+        frontend::run(emulator, graphics, io, sound, config);
     }
 }
 
@@ -98,188 +240,33 @@ fn run_mode_benchmark(mut emulator: Emulator) {
     );
 }
 
-fn run_mode_default(
+fn run_mode_trace(
     mut emulator: Emulator,
-    graphics: Rc<RefCell<GraphicsDevice>>,
-    io: Rc<RefCell<IODevice>>,
+    max_cycles: usize,
+    trace_path: &str,
+    trace_range: Option<(u16, u16)>,
 ) {
-    let mut window = Window::new(
-        "Hasseldorf Emulator",
-        SCREEN_WIDTH_PIXELS,
-        SCREEN_HEIGHT_PIXELS,
-        WindowOptions::default(),
-    ).unwrap_or_else(|e| {
-        println!("Failed to create a window: {}", e);
+    let mut trace_writer = trace::TraceWriter::open(trace_path, trace_range).unwrap_or_else(|e| {
+        println!("Failed to open trace file \"{}\": {}", trace_path, e);
         process::exit(1);
     });
 
-    let mut time_last_step = Instant::now();
-    let mut time_last_render = Instant::now();
-
     let mut total_cycles: usize = 0;
-    let mut previous_keys: Vec<minifb::Key> = Vec::new();
-    while window.is_open() {
-        let since_last_render = Instant::now().duration_since(time_last_render);
-        if since_last_render.subsec_nanos() > 13_000_000u32 {
-            window
-                .update_with_buffer(graphics.borrow().frame_buffer())
-                .unwrap();
-            time_last_render = Instant::now();
-        }
-
-        if let Some(keys_down) = window.get_keys() {
-            for key in &keys_down {
-                if !previous_keys.contains(&key) {
-                    io.borrow_mut().key_down(convert_key(key));
-                }
-            }
-            for key in &previous_keys {
-                if !keys_down.contains(&key) {
-                    io.borrow_mut().key_up(convert_key(key));
-                }
-            }
-            previous_keys = keys_down;
-        }
+    while total_cycles < max_cycles {
+        let pc = emulator.pc();
+        let entry = if trace_writer.wants(pc) {
+            Some(trace::snapshot(&emulator))
+        } else {
+            None
+        };
 
-        let cycles = emulator.step() as u32;
-        total_cycles += cycles as usize;
+        total_cycles += emulator.step() as usize;
 
-        // Slow down so that we're running at approximately 6 MHz
-        loop {
-            let since_last_step = Instant::now().duration_since(time_last_step);
-            // 167 nanoseconds per cycle at 6 MHz
-            if since_last_step.subsec_nanos() > cycles * 167u32 {
-                time_last_step = Instant::now();
-                break;
+        if let Some(entry) = entry {
+            if let Err(e) = trace_writer.write(&entry, total_cycles as u64) {
+                println!("Failed to write trace: {}", e);
+                process::exit(1);
             }
         }
     }
 }
-
-fn convert_key(key: &minifb::Key) -> Key {
-    match *key {
-        minifb::Key::Key0 => Key::Key0,
-        minifb::Key::Key1 => Key::Key1,
-        minifb::Key::Key2 => Key::Key2,
-        minifb::Key::Key3 => Key::Key3,
-        minifb::Key::Key4 => Key::Key4,
-        minifb::Key::Key5 => Key::Key5,
-        minifb::Key::Key6 => Key::Key6,
-        minifb::Key::Key7 => Key::Key7,
-        minifb::Key::Key8 => Key::Key8,
-        minifb::Key::Key9 => Key::Key9,
-
-        minifb::Key::A => Key::A,
-        minifb::Key::B => Key::B,
-        minifb::Key::C => Key::C,
-        minifb::Key::D => Key::D,
-        minifb::Key::E => Key::E,
-        minifb::Key::F => Key::F,
-        minifb::Key::G => Key::G,
-        minifb::Key::H => Key::H,
-        minifb::Key::I => Key::I,
-        minifb::Key::J => Key::J,
-        minifb::Key::K => Key::K,
-        minifb::Key::L => Key::L,
-        minifb::Key::M => Key::M,
-        minifb::Key::N => Key::N,
-        minifb::Key::O => Key::O,
-        minifb::Key::P => Key::P,
-        minifb::Key::Q => Key::Q,
-        minifb::Key::R => Key::R,
-        minifb::Key::S => Key::S,
-        minifb::Key::T => Key::T,
-        minifb::Key::U => Key::U,
-        minifb::Key::V => Key::V,
-        minifb::Key::W => Key::W,
-        minifb::Key::X => Key::X,
-        minifb::Key::Y => Key::Y,
-        minifb::Key::Z => Key::Z,
-
-        minifb::Key::Space => Key::Space,
-        minifb::Key::Tab => Key::Tab,
-
-        minifb::Key::Backslash => Key::Backslash,
-        minifb::Key::Comma => Key::Comma,
-        minifb::Key::Equal => Key::Equal,
-        minifb::Key::LeftBracket => Key::LeftBracket,
-        minifb::Key::Minus => Key::Minus,
-        minifb::Key::Period => Key::Period,
-        minifb::Key::RightBracket => Key::RightBracket,
-        minifb::Key::Semicolon => Key::Semicolon,
-
-        minifb::Key::Slash => Key::Slash,
-        minifb::Key::Enter => Key::Enter,
-
-        minifb::Key::Backspace => Key::Backspace,
-        minifb::Key::Delete => Key::Delete,
-        minifb::Key::End => Key::End,
-
-        minifb::Key::F1 => Key::F1,
-        minifb::Key::F2 => Key::F2,
-        minifb::Key::F3 => Key::F3,
-        minifb::Key::F4 => Key::F4,
-        minifb::Key::F5 => Key::F5,
-        minifb::Key::F6 => Key::F6,
-        minifb::Key::F7 => Key::F7,
-        minifb::Key::F8 => Key::F8,
-        minifb::Key::F9 => Key::F9,
-        minifb::Key::F10 => Key::F10,
-        minifb::Key::F11 => Key::F11,
-        minifb::Key::F12 => Key::F12,
-        minifb::Key::F13 => Key::F13,
-        minifb::Key::F14 => Key::F14,
-        minifb::Key::F15 => Key::F15,
-
-        minifb::Key::Down => Key::Down,
-        minifb::Key::Left => Key::Left,
-        minifb::Key::Right => Key::Right,
-        minifb::Key::Up => Key::Up,
-        minifb::Key::Apostrophe => Key::Apostrophe,
-        minifb::Key::Backquote => Key::Backquote,
-
-        minifb::Key::Escape => Key::Escape,
-
-        minifb::Key::Home => Key::Home,
-        minifb::Key::Insert => Key::Insert,
-        minifb::Key::Menu => Key::Menu,
-
-        minifb::Key::PageDown => Key::PageDown,
-        minifb::Key::PageUp => Key::PageUp,
-
-        minifb::Key::Pause => Key::Pause,
-        minifb::Key::NumLock => Key::NumLock,
-        minifb::Key::CapsLock => Key::CapsLock,
-        minifb::Key::ScrollLock => Key::ScrollLock,
-        minifb::Key::LeftShift => Key::LeftShift,
-        minifb::Key::RightShift => Key::RightShift,
-        minifb::Key::LeftCtrl => Key::LeftCtrl,
-        minifb::Key::RightCtrl => Key::RightCtrl,
-
-        minifb::Key::NumPad0 => Key::NumPad0,
-        minifb::Key::NumPad1 => Key::NumPad1,
-        minifb::Key::NumPad2 => Key::NumPad2,
-        minifb::Key::NumPad3 => Key::NumPad3,
-        minifb::Key::NumPad4 => Key::NumPad4,
-        minifb::Key::NumPad5 => Key::NumPad5,
-        minifb::Key::NumPad6 => Key::NumPad6,
-        minifb::Key::NumPad7 => Key::NumPad7,
-        minifb::Key::NumPad8 => Key::NumPad8,
-        minifb::Key::NumPad9 => Key::NumPad9,
-        minifb::Key::NumPadDot => Key::NumPadDot,
-        minifb::Key::NumPadSlash => Key::NumPadSlash,
-        minifb::Key::NumPadAsterisk => Key::NumPadAsterisk,
-        minifb::Key::NumPadMinus => Key::NumPadMinus,
-        minifb::Key::NumPadPlus => Key::NumPadPlus,
-        minifb::Key::NumPadEnter => Key::NumPadEnter,
-
-        minifb::Key::LeftAlt => Key::LeftAlt,
-        minifb::Key::RightAlt => Key::RightAlt,
-
-        minifb::Key::LeftSuper => Key::LeftSuper,
-        minifb::Key::RightSuper => Key::RightSuper,
-
-        minifb::Key::Unknown => Key::Unknown,
-        minifb::Key::Count => Key::Unknown,
-    }
-}
@@ -0,0 +1,342 @@
+use hassel_emu::hassel::{IODevice, Key};
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::rc::Rc;
+
+const MAGIC: &[u8; 4] = b"HEMV";
+const VERSION: u8 = 1;
+
+/// Computes a simple FNV-1a hash of the ROM so a movie refuses to play
+/// against a ROM it wasn't recorded against.
+pub fn rom_checksum(rom: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for &byte in rom {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+/// Records every key transition dispatched during a session as
+/// `(total_cycles, key, pressed)` tuples, to be flushed to a movie file.
+pub struct MovieRecorder {
+    events: Vec<(u64, Key, bool)>,
+}
+
+impl MovieRecorder {
+    pub fn new() -> Self {
+        MovieRecorder { events: Vec::new() }
+    }
+
+    pub fn record(&mut self, total_cycles: u64, key: Key, pressed: bool) {
+        self.events.push((total_cycles, key, pressed));
+    }
+
+    pub fn write_to_file(&self, path: &str, rom_len: usize, rom_checksum_: u64) -> io::Result<()> {
+        let mut file = File::create(path)?;
+
+        file.write_all(MAGIC)?;
+        file.write_all(&[VERSION])?;
+        file.write_all(&(rom_len as u32).to_le_bytes())?;
+        file.write_all(&rom_checksum_.to_le_bytes())?;
+
+        for &(total_cycles, key, pressed) in &self.events {
+            file.write_all(&total_cycles.to_le_bytes())?;
+            file.write_all(&[key_to_code(key), pressed as u8])?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Replays a previously recorded movie, injecting its events into an
+/// `IODevice` exactly when `total_cycles` reaches each timestamp.
+pub struct MoviePlayer {
+    events: VecDeque<(u64, Key, bool)>,
+}
+
+impl MoviePlayer {
+    pub fn load_from_file(path: &str, rom_len: usize, rom_checksum_: u64) -> Result<Self, String> {
+        let mut file = File::open(path).map_err(|e| format!("Failed to open movie: {}", e))?;
+
+        let mut header = [0u8; 4 + 1 + 4 + 8];
+        file.read_exact(&mut header)
+            .map_err(|e| format!("Failed to read movie header: {}", e))?;
+
+        if &header[0..4] != MAGIC {
+            return Err("Not a Hasseldorf movie file".to_string());
+        }
+        let version = header[4];
+        if version != VERSION {
+            return Err(format!("Unsupported movie version: {}", version));
+        }
+        let header_rom_len = u32::from_le_bytes([header[5], header[6], header[7], header[8]]) as usize;
+        let header_checksum = u64::from_le_bytes([
+            header[9], header[10], header[11], header[12],
+            header[13], header[14], header[15], header[16],
+        ]);
+        if header_rom_len != rom_len || header_checksum != rom_checksum_ {
+            return Err("Movie was recorded against a different ROM".to_string());
+        }
+
+        let mut rest = Vec::new();
+        file.read_to_end(&mut rest)
+            .map_err(|e| format!("Failed to read movie events: {}", e))?;
+
+        let mut events = VecDeque::new();
+        for chunk in rest.chunks_exact(10) {
+            let total_cycles = u64::from_le_bytes([
+                chunk[0], chunk[1], chunk[2], chunk[3],
+                chunk[4], chunk[5], chunk[6], chunk[7],
+            ]);
+            let key = code_to_key(chunk[8]);
+            let pressed = chunk[9] != 0;
+            events.push_back((total_cycles, key, pressed));
+        }
+
+        Ok(MoviePlayer { events })
+    }
+
+    /// Dispatches every recorded event whose timestamp has been reached.
+    pub fn dispatch_due(&mut self, total_cycles: u64, io: &Rc<RefCell<IODevice>>) {
+        while let Some(&(cycle, key, pressed)) = self.events.front() {
+            if cycle > total_cycles {
+                break;
+            }
+            if pressed {
+                io.borrow_mut().key_down(key);
+            } else {
+                io.borrow_mut().key_up(key);
+            }
+            self.events.pop_front();
+        }
+    }
+}
+
+fn key_to_code(key: Key) -> u8 {
+    match key {
+        Key::Key0 => 0,
+        Key::Key1 => 1,
+        Key::Key2 => 2,
+        Key::Key3 => 3,
+        Key::Key4 => 4,
+        Key::Key5 => 5,
+        Key::Key6 => 6,
+        Key::Key7 => 7,
+        Key::Key8 => 8,
+        Key::Key9 => 9,
+        Key::A => 10,
+        Key::B => 11,
+        Key::C => 12,
+        Key::D => 13,
+        Key::E => 14,
+        Key::F => 15,
+        Key::G => 16,
+        Key::H => 17,
+        Key::I => 18,
+        Key::J => 19,
+        Key::K => 20,
+        Key::L => 21,
+        Key::M => 22,
+        Key::N => 23,
+        Key::O => 24,
+        Key::P => 25,
+        Key::Q => 26,
+        Key::R => 27,
+        Key::S => 28,
+        Key::T => 29,
+        Key::U => 30,
+        Key::V => 31,
+        Key::W => 32,
+        Key::X => 33,
+        Key::Y => 34,
+        Key::Z => 35,
+        Key::Space => 36,
+        Key::Tab => 37,
+        Key::Backslash => 38,
+        Key::Comma => 39,
+        Key::Equal => 40,
+        Key::LeftBracket => 41,
+        Key::Minus => 42,
+        Key::Period => 43,
+        Key::RightBracket => 44,
+        Key::Semicolon => 45,
+        Key::Slash => 46,
+        Key::Enter => 47,
+        Key::Backspace => 48,
+        Key::Delete => 49,
+        Key::End => 50,
+        Key::F1 => 51,
+        Key::F2 => 52,
+        Key::F3 => 53,
+        Key::F4 => 54,
+        Key::F5 => 55,
+        Key::F6 => 56,
+        Key::F7 => 57,
+        Key::F8 => 58,
+        Key::F9 => 59,
+        Key::F10 => 60,
+        Key::F11 => 61,
+        Key::F12 => 62,
+        Key::F13 => 63,
+        Key::F14 => 64,
+        Key::F15 => 65,
+        Key::Down => 66,
+        Key::Left => 67,
+        Key::Right => 68,
+        Key::Up => 69,
+        Key::Apostrophe => 70,
+        Key::Backquote => 71,
+        Key::Escape => 72,
+        Key::Home => 73,
+        Key::Insert => 74,
+        Key::Menu => 75,
+        Key::PageDown => 76,
+        Key::PageUp => 77,
+        Key::Pause => 78,
+        Key::NumLock => 79,
+        Key::CapsLock => 80,
+        Key::ScrollLock => 81,
+        Key::LeftShift => 82,
+        Key::RightShift => 83,
+        Key::LeftCtrl => 84,
+        Key::RightCtrl => 85,
+        Key::NumPad0 => 86,
+        Key::NumPad1 => 87,
+        Key::NumPad2 => 88,
+        Key::NumPad3 => 89,
+        Key::NumPad4 => 90,
+        Key::NumPad5 => 91,
+        Key::NumPad6 => 92,
+        Key::NumPad7 => 93,
+        Key::NumPad8 => 94,
+        Key::NumPad9 => 95,
+        Key::NumPadDot => 96,
+        Key::NumPadSlash => 97,
+        Key::NumPadAsterisk => 98,
+        Key::NumPadMinus => 99,
+        Key::NumPadPlus => 100,
+        Key::NumPadEnter => 101,
+        Key::LeftAlt => 102,
+        Key::RightAlt => 103,
+        Key::LeftSuper => 104,
+        Key::RightSuper => 105,
+        Key::Unknown => 106,
+    }
+}
+fn code_to_key(code: u8) -> Key {
+    match code {
+        0 => Key::Key0,
+        1 => Key::Key1,
+        2 => Key::Key2,
+        3 => Key::Key3,
+        4 => Key::Key4,
+        5 => Key::Key5,
+        6 => Key::Key6,
+        7 => Key::Key7,
+        8 => Key::Key8,
+        9 => Key::Key9,
+        10 => Key::A,
+        11 => Key::B,
+        12 => Key::C,
+        13 => Key::D,
+        14 => Key::E,
+        15 => Key::F,
+        16 => Key::G,
+        17 => Key::H,
+        18 => Key::I,
+        19 => Key::J,
+        20 => Key::K,
+        21 => Key::L,
+        22 => Key::M,
+        23 => Key::N,
+        24 => Key::O,
+        25 => Key::P,
+        26 => Key::Q,
+        27 => Key::R,
+        28 => Key::S,
+        29 => Key::T,
+        30 => Key::U,
+        31 => Key::V,
+        32 => Key::W,
+        33 => Key::X,
+        34 => Key::Y,
+        35 => Key::Z,
+        36 => Key::Space,
+        37 => Key::Tab,
+        38 => Key::Backslash,
+        39 => Key::Comma,
+        40 => Key::Equal,
+        41 => Key::LeftBracket,
+        42 => Key::Minus,
+        43 => Key::Period,
+        44 => Key::RightBracket,
+        45 => Key::Semicolon,
+        46 => Key::Slash,
+        47 => Key::Enter,
+        48 => Key::Backspace,
+        49 => Key::Delete,
+        50 => Key::End,
+        51 => Key::F1,
+        52 => Key::F2,
+        53 => Key::F3,
+        54 => Key::F4,
+        55 => Key::F5,
+        56 => Key::F6,
+        57 => Key::F7,
+        58 => Key::F8,
+        59 => Key::F9,
+        60 => Key::F10,
+        61 => Key::F11,
+        62 => Key::F12,
+        63 => Key::F13,
+        64 => Key::F14,
+        65 => Key::F15,
+        66 => Key::Down,
+        67 => Key::Left,
+        68 => Key::Right,
+        69 => Key::Up,
+        70 => Key::Apostrophe,
+        71 => Key::Backquote,
+        72 => Key::Escape,
+        73 => Key::Home,
+        74 => Key::Insert,
+        75 => Key::Menu,
+        76 => Key::PageDown,
+        77 => Key::PageUp,
+        78 => Key::Pause,
+        79 => Key::NumLock,
+        80 => Key::CapsLock,
+        81 => Key::ScrollLock,
+        82 => Key::LeftShift,
+        83 => Key::RightShift,
+        84 => Key::LeftCtrl,
+        85 => Key::RightCtrl,
+        86 => Key::NumPad0,
+        87 => Key::NumPad1,
+        88 => Key::NumPad2,
+        89 => Key::NumPad3,
+        90 => Key::NumPad4,
+        91 => Key::NumPad5,
+        92 => Key::NumPad6,
+        93 => Key::NumPad7,
+        94 => Key::NumPad8,
+        95 => Key::NumPad9,
+        96 => Key::NumPadDot,
+        97 => Key::NumPadSlash,
+        98 => Key::NumPadAsterisk,
+        99 => Key::NumPadMinus,
+        100 => Key::NumPadPlus,
+        101 => Key::NumPadEnter,
+        102 => Key::LeftAlt,
+        103 => Key::RightAlt,
+        104 => Key::LeftSuper,
+        105 => Key::RightSuper,
+        106 => Key::Unknown,
+        _ => Key::Unknown,
+    }
+}
@@ -0,0 +1,36 @@
+use hassel_emu::emulator::Emulator;
+
+use std::fs::File;
+use std::io::prelude::*;
+
+/// Returns the path of the save-state file for a given ROM path.
+pub fn state_path(rom_path: &str) -> String {
+    format!("{}.state", rom_path)
+}
+
+/// Snapshots the emulator and writes it to `path`, printing a status line
+/// either way so the hotkey has visible feedback.
+pub fn save(emulator: &Emulator, path: &str) {
+    let data = emulator.save_state();
+
+    let result = File::create(path).and_then(|mut file| file.write_all(&data));
+    match result {
+        Ok(()) => println!("Saved state to \"{}\" ({} bytes)", path, data.len()),
+        Err(e) => println!("Failed to save state to \"{}\": {}", path, e),
+    }
+}
+
+/// Reads `path` and restores it into `emulator`, leaving the emulator
+/// untouched if the file is missing or the snapshot is rejected.
+pub fn load(emulator: &mut Emulator, path: &str) {
+    let mut data = Vec::new();
+    if let Err(e) = File::open(path).and_then(|mut file| file.read_to_end(&mut data)) {
+        println!("Failed to load state from \"{}\": {}", path, e);
+        return;
+    }
+
+    match emulator.load_state(&data) {
+        Ok(()) => println!("Loaded state from \"{}\"", path),
+        Err(e) => println!("Failed to load state from \"{}\": {:?}", path, e),
+    }
+}
@@ -0,0 +1,87 @@
+use hassel_emu::hassel::GraphicsDevice;
+use hassel_emu::emulator::Emulator;
+
+use std::cell::RefCell;
+use std::fs::File;
+use std::io::prelude::*;
+use std::process;
+use std::rc::Rc;
+
+/// Runs the emulator headlessly for up to `max_cycles` and either compares
+/// the resulting frame buffer against `expected_path`, or (when no expected
+/// file is given) writes the captured frame buffer to `out_path` so it can be
+/// promoted to a new golden file.
+pub fn run(
+    mut emulator: Emulator,
+    graphics: Rc<RefCell<GraphicsDevice>>,
+    max_cycles: usize,
+    expected_path: Option<&str>,
+    out_path: &str,
+) {
+    let mut total_cycles: usize = 0;
+    while total_cycles < max_cycles {
+        total_cycles += emulator.step() as usize;
+    }
+
+    let actual = capture_frame_buffer(&graphics);
+
+    match expected_path {
+        Some(expected_path) => compare_against_golden(&actual, expected_path),
+        None => write_golden(&actual, out_path),
+    }
+}
+
+fn capture_frame_buffer(graphics: &Rc<RefCell<GraphicsDevice>>) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    for pixel in graphics.borrow().frame_buffer() {
+        bytes.extend_from_slice(&pixel.to_le_bytes());
+    }
+    bytes
+}
+
+fn compare_against_golden(actual: &[u8], expected_path: &str) {
+    let mut expected_file = File::open(expected_path).unwrap_or_else(|e| {
+        println!("Failed to open expected file \"{}\": {}", expected_path, e);
+        process::exit(1);
+    });
+
+    let mut expected = Vec::new();
+    expected_file.read_to_end(&mut expected).unwrap_or_else(|e| {
+        println!("Failed to read expected file \"{}\": {}", expected_path, e);
+        process::exit(1);
+    });
+
+    if actual == expected.as_slice() {
+        println!("PASS: output matches {}", expected_path);
+        return;
+    }
+
+    let first_diff = actual
+        .iter()
+        .zip(expected.iter())
+        .position(|(a, e)| a != e)
+        .unwrap_or_else(|| actual.len().min(expected.len()));
+
+    println!(
+        "FAIL: output differs from {} at byte offset {} (actual {} bytes, expected {} bytes)",
+        expected_path,
+        first_diff,
+        actual.len(),
+        expected.len()
+    );
+    process::exit(1);
+}
+
+fn write_golden(actual: &[u8], out_path: &str) {
+    let mut out_file = File::create(out_path).unwrap_or_else(|e| {
+        println!("Failed to create output file \"{}\": {}", out_path, e);
+        process::exit(1);
+    });
+
+    out_file.write_all(actual).unwrap_or_else(|e| {
+        println!("Failed to write output file \"{}\": {}", out_path, e);
+        process::exit(1);
+    });
+
+    println!("Wrote {} bytes to {}", actual.len(), out_path);
+}
@@ -0,0 +1,110 @@
+use hassel_emu::emulator::Emulator;
+
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::io::BufWriter;
+
+/// A snapshot of everything needed to log one executed instruction, captured
+/// before the instruction runs so the logged registers reflect the CPU state
+/// the instruction actually saw.
+pub struct TraceEntry {
+    pc: u16,
+    opcode_bytes: Vec<u8>,
+    mnemonic: String,
+    a: u8,
+    x: u8,
+    y: u8,
+    sp: u8,
+    status: u8,
+}
+
+/// Captures a `TraceEntry` for the instruction about to execute at the
+/// emulator's current PC.
+pub fn snapshot(emulator: &Emulator) -> TraceEntry {
+    let pc = emulator.pc();
+    let decoded = emulator.disassemble_at(pc);
+    let opcode_bytes = (0..decoded.length as u16)
+        .map(|offset| emulator.peek(pc.wrapping_add(offset)))
+        .collect();
+
+    TraceEntry {
+        pc,
+        opcode_bytes,
+        mnemonic: decoded.mnemonic,
+        a: emulator.reg_a(),
+        x: emulator.reg_x(),
+        y: emulator.reg_y(),
+        sp: emulator.reg_sp(),
+        status: emulator.status(),
+    }
+}
+
+/// Writes one line per executed instruction to a buffered file, optionally
+/// restricted to an inclusive `[lo, hi]` PC range.
+pub struct TraceWriter {
+    writer: BufWriter<File>,
+    range: Option<(u16, u16)>,
+}
+
+impl TraceWriter {
+    pub fn open(path: &str, range: Option<(u16, u16)>) -> io::Result<Self> {
+        Ok(TraceWriter {
+            writer: BufWriter::new(File::create(path)?),
+            range,
+        })
+    }
+
+    /// Returns whether `pc` falls within the configured address filter, so
+    /// callers can skip the cost of disassembling instructions outside it.
+    pub fn wants(&self, pc: u16) -> bool {
+        match self.range {
+            Some((lo, hi)) => pc >= lo && pc <= hi,
+            None => true,
+        }
+    }
+
+    pub fn write(&mut self, entry: &TraceEntry, cycle_count: u64) -> io::Result<()> {
+        let bytes = entry
+            .opcode_bytes
+            .iter()
+            .map(|byte| format!("{:02X}", byte))
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        writeln!(
+            self.writer,
+            "{:04X}  {:<8} {:<20} A:{:02X} X:{:02X} Y:{:02X} SP:{:02X} P:{:02X} CYC:{}",
+            entry.pc, bytes, entry.mnemonic, entry.a, entry.x, entry.y, entry.sp, entry.status, cycle_count
+        )
+    }
+
+    /// Flushes buffered writes to disk. The `winit` event loop never
+    /// guarantees its captured state is dropped on exit, so callers must
+    /// call this explicitly (e.g. on `CloseRequested`) instead of relying
+    /// on `BufWriter`'s flush-on-drop.
+    pub fn finish(mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Parses a `LO:HI` address range. Each bound is read as hex, with or
+/// without a `0x` prefix.
+pub fn parse_range(spec: &str) -> Result<(u16, u16), String> {
+    let mut parts = spec.splitn(2, ':');
+    let lo = parts.next().ok_or_else(|| "missing low address".to_string())?;
+    let hi = parts
+        .next()
+        .ok_or_else(|| "expected LO:HI, e.g. 8000:80FF".to_string())?;
+
+    Ok((parse_addr(lo)?, parse_addr(hi)?))
+}
+
+fn parse_addr(s: &str) -> Result<u16, String> {
+    let s = s.trim();
+    if let Some(hex) = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        u16::from_str_radix(hex, 16).map_err(|e| e.to_string())
+    } else {
+        u16::from_str_radix(s, 16).map_err(|e| e.to_string())
+    }
+}